@@ -10,9 +10,80 @@ use std::{cell::RefCell, path::Path, rc::Rc};
 struct Stack<'a> {
     name: &'a str,
     value: usize,
+    /// difference between the *after* and *before* value at this path,
+    /// used by d3-flame-graph's differential mode. Omitted when zero so
+    /// the non-differential output stays unchanged.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    delta: isize,
+    /// raw number of collapsed-stack lines that touched this frame, kept
+    /// distinct from the `value` self-weight. Omitted when zero.
+    #[serde(default, skip_serializing_if = "count_is_zero")]
+    count: usize,
     children: Vec<Rc<RefCell<Stack<'a>>>>,
 }
 
+fn is_zero(delta: &isize) -> bool {
+    *delta == 0
+}
+
+fn count_is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// what the `value` counts represent, so the flamegraph can render
+/// human-friendly totals instead of raw sample counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Unit {
+    /// raw sample counts, rendered as integers
+    #[default]
+    Samples,
+    /// nanoseconds, rendered as seconds in tooltips
+    Nanoseconds,
+    /// bytes, rendered as KB/MB/GB
+    Bytes,
+}
+
+/// how to render the [`Stack`] hierarchy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// the classic d3-flame-graph flamegraph
+    #[default]
+    Flamegraph,
+    /// a radial sunburst where flamegraph width becomes arc angle
+    Sunburst,
+    /// a top-down icicle diagram
+    Icicle,
+}
+
+impl Layout {
+    /// the `mode` string consumed by the partition template's JS.
+    fn partition_mode(self) -> &'static str {
+        match self {
+            Layout::Sunburst => "sunburst",
+            Layout::Icicle => "icicle",
+            Layout::Flamegraph => "flamegraph",
+        }
+    }
+}
+
+impl Unit {
+    /// the body of the JS `formatValue(value)` function used to render totals
+    /// in the details pane for this unit.
+    fn format_js(self) -> &'static str {
+        match self {
+            Unit::Samples => "return Math.round(value).toLocaleString();",
+            Unit::Nanoseconds => "return (value / 1e9).toFixed(2) + \"s\";",
+            Unit::Bytes => {
+                "if (value < 1024) return value + \" B\";\n      \
+                 var units = [\"KB\", \"MB\", \"GB\", \"TB\"];\n      \
+                 var i = -1;\n      \
+                 do { value /= 1024; i++; } while (value >= 1024 && i < units.length - 1);\n      \
+                 return value.toFixed(1) + \" \" + units[i];"
+            }
+        }
+    }
+}
+
 impl<'a> Stack<'a> {
     fn new(name: &'a str) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Self {
@@ -55,6 +126,12 @@ pub fn collapse_to_json(stacks: &[&str]) -> String {
             crumbs.truncate(depth);
         }
 
+        // record that this collapsed-stack line touched every frame on the path,
+        // independent of the aggregated sample `value`.
+        for node in crumbs.iter().skip(1) {
+            node.borrow_mut().count += 1;
+        }
+
         let self_value = true;
         if self_value {
             // if we were to use selfValue(true), this inserts values only
@@ -75,6 +152,75 @@ pub fn collapse_to_json(stacks: &[&str]) -> String {
     serde_json::to_string(&root).expect("serialization to json")
 }
 
+/// descends from `root` following `names`, creating missing nodes along the
+/// way, and returns the leaf node. Unlike [`collapse_to_json`]'s crumb trail
+/// this matches existing children by name, so multiple passes (e.g. a before
+/// and an after capture) merge into a single tree.
+fn descend<'a>(
+    root: &Rc<RefCell<Stack<'a>>>,
+    names: impl Iterator<Item = &'a str>,
+) -> Rc<RefCell<Stack<'a>>> {
+    let mut node = root.clone();
+
+    for name in names {
+        let child = node
+            .borrow()
+            .children
+            .iter()
+            .find(|c| c.borrow().name == name)
+            .cloned();
+
+        node = match child {
+            Some(child) => child,
+            None => {
+                let child = Stack::new(name);
+                node.borrow_mut().children.push(child.clone());
+                child
+            }
+        };
+    }
+
+    node
+}
+
+/// accumulates the leaf self-value of each collapsed `stack` line into the tree
+/// rooted at `root`, applying it through `apply`.
+fn accumulate_diff<'a>(root: &Rc<RefCell<Stack<'a>>>, stacks: &[&'a str], apply: impl Fn(&mut Stack, usize)) {
+    for stack in stacks {
+        let mut parts = stack.split(' ');
+        let names = parts.next().map(|v| v.split(';')).expect("stack");
+        let count = parts
+            .next()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(1);
+
+        let leaf = descend(root, names);
+        apply(&mut leaf.borrow_mut(), count);
+    }
+}
+
+/// builds a differential flamegraph tree comparing a `before` and an `after`
+/// capture. Both `value` (the after self-value, used for frame width in
+/// selfValue mode) and `delta = after - before` are accumulated as leaf
+/// self-values on the same scale, so d3-flame-graph aggregates them up the
+/// tree identically. Paths that only appear in `before` are still inserted,
+/// ending up with `value == 0` and a negative delta. Frames whose delta works
+/// out to zero serialize without a `delta` field, which d3-flame-graph treats
+/// as unchanged.
+pub fn collapse_to_diff_json(before: &[&str], after: &[&str]) -> String {
+    let root = Stack::new("");
+
+    // after stacks set the width-bearing leaf self-value and the positive part
+    // of the leaf delta; before stacks subtract their leaf self-value.
+    accumulate_diff(&root, after, |node, count| {
+        node.value += count;
+        node.delta += count as isize;
+    });
+    accumulate_diff(&root, before, |node, count| node.delta -= count as isize);
+
+    serde_json::to_string(&root).expect("serialization to json")
+}
+
 #[test]
 fn test_serialization() {
     let x = [
@@ -89,7 +235,7 @@ fn test_serialization() {
 
     assert_eq!(
         collapse_to_json(&x),
-        r##"{"name":"","value":9,"children":[{"name":"a","value":8,"children":[{"name":"b","value":7,"children":[{"name":"c","value":2,"children":[{"name":"d","value":1,"children":[]}]},{"name":"e","value":3,"children":[]}]}]},{"name":"f","value":1,"children":[{"name":"g","value":1,"children":[]}]}]}"##
+        r##"{"name":"","value":0,"children":[{"name":"a","value":1,"count":6,"children":[{"name":"b","value":2,"count":5,"children":[{"name":"c","value":1,"count":2,"children":[{"name":"d","value":1,"count":1,"children":[]}]},{"name":"e","value":3,"count":1,"children":[]}]}]},{"name":"f","value":0,"count":1,"children":[{"name":"g","value":1,"count":1,"children":[]}]}]}"##
     );
 
     let mut test = Stack::default();
@@ -112,9 +258,20 @@ fn test_serialization() {
     );
 }
 
-pub fn generate_html_file(filename: &Path, stacks: &[&str]) {
+pub fn generate_html_file(filename: &Path, stacks: &[&str], unit: Unit, layout: Layout) {
     let data = collapse_to_json(stacks);
-    let html = flamegraph_html(&data);
+    let html = match layout {
+        Layout::Flamegraph => flamegraph_html(&data, false, unit),
+        Layout::Sunburst | Layout::Icicle => partition_html(&data, unit, layout),
+    };
+    std::fs::write(&filename, &html).expect("Unable to write stack html file");
+}
+
+/// renders a differential flamegraph comparing a `before` and `after` capture,
+/// colouring frames that grew red and frames that shrank blue.
+pub fn generate_diff_html_file(filename: &Path, before: &[&str], after: &[&str]) {
+    let data = collapse_to_diff_json(before, after);
+    let html = flamegraph_html(&data, true, Unit::Samples);
     std::fs::write(&filename, &html).expect("Unable to write stack html file");
 }
 
@@ -127,8 +284,7 @@ const HTML_TEMPLATE: &str = r##"
     <meta http-equiv="X-UA-Compatible" content="IE=edge">
     <meta name="viewport" content="width=device-width, initial-scale=1">
 
-    <link rel="stylesheet" href="https://maxcdn.bootstrapcdn.com/bootstrap/3.3.7/css/bootstrap.min.css">
-    <link rel="stylesheet" type="text/css" href="https://cdn.jsdelivr.net/gh/spiermar/d3-flame-graph@2.0.3/dist/d3-flamegraph.css">
+    {styles}
 
     <style>
 
@@ -189,14 +345,7 @@ const HTML_TEMPLATE: &str = r##"
       </div>
     </div>
 
-    <!-- D3.js -->
-    <script src="https://d3js.org/d3.v4.min.js" charset="utf-8"></script>
-
-    <!-- d3-tip -->
-    <script type="text/javascript" src=https://cdnjs.cloudflare.com/ajax/libs/d3-tip/0.9.1/d3-tip.min.js></script>
-
-    <!-- d3-flamegraph -->
-    <script type="text/javascript" src="https://cdn.jsdelivr.net/gh/spiermar/d3-flame-graph@2.0.3/dist/d3-flamegraph.min.js"></script>
+    {scripts}
 
     <script type="text/javascript">
       var data = {stack};
@@ -214,18 +363,38 @@ const HTML_TEMPLATE: &str = r##"
       //.sort(function(a,b){ return d3.descending(a.name, b.name);})
       .title("")
       .onClick(onClick)
-      .differential(false)
+      .differential({differential})
       .selfValue(!false);
 
+    // Render values in the configured unit instead of raw sample counts.
+    function formatValue(value) {
+      {value_format}
+    }
+    flameGraph.label(function (d) {
+      return d.data.name + " (" + formatValue(d.value) + ")";
+    });
 
-    // Example on how to use custom tooltips using d3-tip.
-    // var tip = d3.tip()
-    //   .direction("s")
-    //   .offset([8, 0])
-    //   .attr('class', 'd3-flame-graph-tip')
-    //   .html(function(d) { return "name: " + d.data.name + ", value: " + d.data.value; });
 
-    // flameGraph.tooltip(tip);
+    // Custom tooltip showing name, formatted value, raw sample count and the
+    // share of the whole profile this frame represents.
+    var tip = d3.tip()
+      .direction("s")
+      .offset([8, 0])
+      .attr('class', 'd3-flame-graph-tip')
+      .html(function(d) {
+        // walk up to the aggregated root so the percentage is a share of the
+        // whole profile; data.value is the serialized root self-value, which
+        // is always 0.
+        var root = d;
+        while (root.parent) { root = root.parent; }
+        var pct = root.value ? (100 * d.value / root.value).toFixed(2) : "0.00";
+        return "name: " + d.data.name +
+          "<br>value: " + formatValue(d.value) +
+          "<br>count: " + (d.data.count || 0) +
+          "<br>" + pct + "% of root";
+      });
+
+    flameGraph.tooltip(tip);
 
     d3.select("#chart")
       .datum(data)
@@ -243,13 +412,6 @@ const HTML_TEMPLATE: &str = r##"
     // Example of how to set fixed chart height
     // flameGraph.height(540);
 
-    d3.json("stacks.json", function(error, data) {
-      if (error) return console.warn(error);
-      d3.select("#chart")
-          .datum(data)
-          .call(flameGraph);
-    });
-
     document.getElementById("form").addEventListener("submit", function(event){
       event.preventDefault();
       search();
@@ -277,8 +439,170 @@ const HTML_TEMPLATE: &str = r##"
 </html>
 "##;
 
-fn flamegraph_html(stacks: &str) -> String {
+// CDN references for the page's styles and scripts; every generated page
+// loads them over the network.
+const CDN_STYLES: &str = r##"<link rel="stylesheet" href="https://maxcdn.bootstrapcdn.com/bootstrap/3.3.7/css/bootstrap.min.css">
+    <link rel="stylesheet" type="text/css" href="https://cdn.jsdelivr.net/gh/spiermar/d3-flame-graph@2.0.3/dist/d3-flamegraph.css">"##;
+const CDN_SCRIPTS: &str = r##"<!-- D3.js -->
+    <script src="https://d3js.org/d3.v4.min.js" charset="utf-8"></script>
+
+    <!-- d3-tip -->
+    <script type="text/javascript" src=https://cdnjs.cloudflare.com/ajax/libs/d3-tip/0.9.1/d3-tip.min.js></script>
+
+    <!-- d3-flamegraph -->
+    <script type="text/javascript" src="https://cdn.jsdelivr.net/gh/spiermar/d3-flame-graph@2.0.3/dist/d3-flamegraph.min.js"></script>"##;
+
+/// builds the `(styles, scripts)` markup for the head/body as CDN references.
+fn assets() -> (String, String) {
+    (CDN_STYLES.to_string(), CDN_SCRIPTS.to_string())
+}
+
+fn flamegraph_html(stacks: &str, differential: bool, unit: Unit) -> String {
+    let (styles, scripts) = assets();
+
     HTML_TEMPLATE
         .replace("{stack}", stacks)
         .replace("{title}", "profile-bee")
+        .replace("{differential}", if differential { "true" } else { "false" })
+        .replace("{styles}", &styles)
+        .replace("{scripts}", &scripts)
+        .replace("{value_format}", unit.format_js())
+}
+
+// Renders the same hierarchy through a D3 partition layout, as either a radial
+// sunburst or a top-down icicle, following the R2D3 sunburst approach.
+const PARTITION_TEMPLATE: &str = r##"
+<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <meta http-equiv="X-UA-Compatible" content="IE=edge">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+
+    {styles}
+
+    <style>
+    body {
+      padding-top: 20px;
+      padding-bottom: 20px;
+    }
+    .header {
+      padding-bottom: 20px;
+      padding-right: 15px;
+      padding-left: 15px;
+      border-bottom: 1px solid #e5e5e5;
+    }
+    .header h3 {
+      margin-top: 0;
+      margin-bottom: 0;
+      line-height: 40px;
+    }
+    .container {
+      max-width: 990px;
+    }
+    #chart path, #chart rect {
+      stroke: #fff;
+    }
+    #chart text {
+      pointer-events: none;
+    }
+    </style>
+    <title>{title}</title>
+  </head>
+  <body>
+    <div class="container">
+      <div class="header clearfix">
+        <h3 class="text-muted">{title}</h3>
+      </div>
+      <div id="chart">
+      </div>
+    </div>
+
+    {scripts}
+
+    <script type="text/javascript">
+      var data = {stack};
+    </script>
+
+    <script type="text/javascript">
+    function formatValue(value) {
+      {value_format}
+    }
+
+    var mode = "{partition_mode}";
+    var width = 960, height = 600;
+
+    var svg = d3.select("#chart").append("svg")
+      .attr("width", width)
+      .attr("height", height);
+
+    var color = d3.scaleOrdinal(d3.schemeCategory20);
+
+    var root = d3.hierarchy(data)
+      .sum(function(d) { return d.value || 0; })
+      .sort(function(a, b) { return b.value - a.value; });
+
+    function fill(d) {
+      return color((d.children ? d : d.parent).data.name);
+    }
+
+    function title(d) {
+      return d.data.name + "\n" + formatValue(d.value);
+    }
+
+    if (mode === "sunburst") {
+      var radius = Math.min(width, height) / 2;
+      var g = svg.append("g")
+        .attr("transform", "translate(" + width / 2 + "," + height / 2 + ")");
+
+      d3.partition().size([2 * Math.PI, radius])(root);
+
+      var arc = d3.arc()
+        .startAngle(function(d) { return d.x0; })
+        .endAngle(function(d) { return d.x1; })
+        .innerRadius(function(d) { return d.y0; })
+        .outerRadius(function(d) { return d.y1; });
+
+      g.selectAll("path")
+        .data(root.descendants())
+        .enter().append("path")
+          .attr("display", function(d) { return d.depth ? null : "none"; })
+          .attr("d", arc)
+          .style("fill", fill)
+          .append("title").text(title);
+    } else {
+      d3.partition().size([width, height]).padding(1)(root);
+
+      var cell = svg.selectAll("g")
+        .data(root.descendants())
+        .enter().append("g")
+          .attr("transform", function(d) { return "translate(" + d.x0 + "," + d.y0 + ")"; });
+
+      cell.append("rect")
+        .attr("width", function(d) { return d.x1 - d.x0; })
+        .attr("height", function(d) { return d.y1 - d.y0; })
+        .style("fill", fill)
+        .append("title").text(title);
+
+      cell.append("text")
+        .attr("x", 4)
+        .attr("y", 13)
+        .style("font", "10px Verdana")
+        .text(function(d) { return d.data.name; });
+    }
+    </script>
+  </body>
+</html>
+"##;
+
+fn partition_html(stacks: &str, unit: Unit, layout: Layout) -> String {
+    let (styles, scripts) = assets();
+
+    PARTITION_TEMPLATE
+        .replace("{stack}", stacks)
+        .replace("{title}", "profile-bee")
+        .replace("{styles}", &styles)
+        .replace("{scripts}", &scripts)
+        .replace("{value_format}", unit.format_js())
+        .replace("{partition_mode}", layout.partition_mode())
 }